@@ -0,0 +1,115 @@
+// Chunking support for blobs that don't fit in a single reveal
+// transaction's witness. Each segment is tagged with a fixed header so
+// the segments can be recognized and reassembled in order regardless of
+// the order their transactions are discovered on chain.
+use crate::BitcoinError;
+
+// HEADER_LEN is the size, in bytes, of the header prepended to every
+// segment: a 16-byte blob id, a u32 segment index, a u32 total segment
+// count, and the u32 total byte length of the reassembled blob.
+pub const HEADER_LEN: usize = 16 + 4 + 4 + 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SegmentHeader {
+    pub blob_id: [u8; 16],
+    pub index: u32,
+    pub total_segments: u32,
+    pub total_len: u32,
+}
+
+impl SegmentHeader {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_LEN);
+        out.extend_from_slice(&self.blob_id);
+        out.extend_from_slice(&self.index.to_be_bytes());
+        out.extend_from_slice(&self.total_segments.to_be_bytes());
+        out.extend_from_slice(&self.total_len.to_be_bytes());
+        out
+    }
+
+    // decode reads a header off the front of bytes, returning it along
+    // with the remaining, un-consumed payload.
+    pub fn decode(bytes: &[u8]) -> Option<(Self, &[u8])> {
+        if bytes.len() < HEADER_LEN {
+            return None;
+        }
+        let mut blob_id = [0u8; 16];
+        blob_id.copy_from_slice(&bytes[0..16]);
+        let index = u32::from_be_bytes(bytes[16..20].try_into().ok()?);
+        let total_segments = u32::from_be_bytes(bytes[20..24].try_into().ok()?);
+        let total_len = u32::from_be_bytes(bytes[24..28].try_into().ok()?);
+        Some((
+            SegmentHeader {
+                blob_id,
+                index,
+                total_segments,
+                total_len,
+            },
+            &bytes[HEADER_LEN..],
+        ))
+    }
+}
+
+// segment_blob splits data into segments of at most budget bytes each,
+// every one prepended with a header that ties it back to blob_id so
+// reassemble can later recognize the complete set.
+pub fn segment_blob(data: &[u8], blob_id: [u8; 16], budget: usize) -> Vec<Vec<u8>> {
+    let chunks = crate::chunk_slice(data, budget);
+    let total_segments = chunks.len() as u32;
+    let total_len = data.len() as u32;
+
+    chunks
+        .iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            let header = SegmentHeader {
+                blob_id,
+                index: i as u32,
+                total_segments,
+                total_len,
+            };
+            let mut out = header.encode();
+            out.extend_from_slice(chunk);
+            out
+        })
+        .collect()
+}
+
+// reassemble validates a set of decoded (header, payload) segments and
+// concatenates them back into the original blob, in index order. It
+// errors if any segment's blob id, total-segment count, or total length
+// disagrees with the rest, or if the indices aren't exactly 0..total with
+// no gaps or duplicates.
+pub fn reassemble(mut segments: Vec<(SegmentHeader, Vec<u8>)>) -> Result<Vec<u8>, BitcoinError> {
+    if segments.is_empty() {
+        return Err(BitcoinError::InvalidTxHash);
+    }
+
+    segments.sort_by_key(|(header, _)| header.index);
+
+    let blob_id = segments[0].0.blob_id;
+    let total_segments = segments[0].0.total_segments;
+    let total_len = segments[0].0.total_len;
+
+    if segments.len() as u32 != total_segments {
+        return Err(BitcoinError::InvalidTxHash);
+    }
+
+    let mut out = Vec::with_capacity(total_len as usize);
+    for (i, (header, payload)) in segments.iter().enumerate() {
+        if header.blob_id != blob_id
+            || header.total_segments != total_segments
+            || header.total_len != total_len
+            || header.index != i as u32
+        {
+            return Err(BitcoinError::InvalidTxHash);
+        }
+        out.extend_from_slice(payload);
+    }
+
+    if out.len() as u32 != total_len {
+        return Err(BitcoinError::InvalidTxHash);
+    }
+
+    Ok(out)
+}
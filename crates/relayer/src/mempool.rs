@@ -0,0 +1,125 @@
+// Mempool watcher: lets callers observe DA writes before they're mined,
+// by polling the mempool plus a few recent blocks and tracking how many
+// confirmations each pending write has accumulated.
+use std::collections::{HashMap, HashSet};
+
+use bitcoin::{ScriptBuf, Transaction, Txid};
+
+use crate::{extract_push_data, tapscript_witness, BitcoinError, Relayer};
+
+struct PendingWrite {
+    blob: Vec<u8>,
+    confirmations: u32,
+}
+
+// MempoolWatcher caches pending DA writes keyed by the reveal
+// transaction's output script_pubkey, so the same write is recognized
+// whether it's still unconfirmed or has since been mined under a
+// different txid (e.g. after a reorg re-mines it).
+pub struct MempoolWatcher<'a> {
+    relayer: &'a Relayer,
+    safety_margin: u32,
+    cache: HashMap<ScriptBuf, (Txid, PendingWrite)>,
+}
+
+impl<'a> MempoolWatcher<'a> {
+    pub fn new(relayer: &'a Relayer, safety_margin: u32) -> Self {
+        MempoolWatcher {
+            relayer,
+            safety_margin,
+            cache: HashMap::new(),
+        }
+    }
+
+    // tick polls the current mempool plus the last `safety_margin` + 1
+    // blocks (tip included): newly seen protocol-tagged reveal
+    // transactions are inserted at zero confirmations, writes that have
+    // since appeared in a block have their confirmation count bumped, and
+    // anything that has reached the safety margin - or that neither
+    // confirmed nor is still in the mempool - is evicted.
+    //
+    // This is a blocking call: every RPC it makes goes through the
+    // synchronous bitcoincore_rpc client, so callers on an async executor
+    // should run it via e.g. spawn_blocking rather than awaiting it inline.
+    pub fn tick(&mut self) -> Result<(), BitcoinError> {
+        let mempool_txids: HashSet<Txid> = self
+            .relayer
+            .get_raw_mempool()
+            .map_err(|_| BitcoinError::TransactionErr)?
+            .into_iter()
+            .collect();
+
+        for txid in &mempool_txids {
+            if self.cache.values().any(|(cached_txid, _)| cached_txid == txid) {
+                continue;
+            }
+            if let Ok(tx) = self.relayer.get_raw_transaction(txid) {
+                if let (Some(blob), Some(out)) =
+                    (decode_reveal(&tx, self.relayer.protocol_id()), tx.output.first())
+                {
+                    self.cache.insert(
+                        out.script_pubkey.clone(),
+                        (*txid, PendingWrite {
+                            blob,
+                            confirmations: 0,
+                        }),
+                    );
+                }
+            }
+        }
+
+        let tip = self
+            .relayer
+            .get_block_count()
+            .map_err(|_| BitcoinError::TransactionErr)?;
+        let start = tip.saturating_sub(self.safety_margin as u64);
+
+        for height in start..=tip {
+            let hash = self
+                .relayer
+                .get_block_hash(height)
+                .map_err(|_| BitcoinError::InvalidTxHash)?;
+            let block = self
+                .relayer
+                .get_block(&hash)
+                .map_err(|_| BitcoinError::InvalidTxHash)?;
+
+            for tx in block.txdata.iter() {
+                if let Some(out) = tx.output.first() {
+                    if let Some((cached_txid, pending)) = self.cache.get_mut(&out.script_pubkey) {
+                        *cached_txid = tx.txid();
+                        pending.confirmations = pending.confirmations.max((tip - height + 1) as u32);
+                    }
+                }
+            }
+        }
+
+        self.cache.retain(|_, (txid, pending)| {
+            pending.confirmations < self.safety_margin
+                && (pending.confirmations > 0 || mempool_txids.contains(txid))
+        });
+
+        Ok(())
+    }
+
+    // pending_writes returns every tracked write's current reveal txid,
+    // blob, and confirmation count.
+    pub fn pending_writes(&self) -> Vec<(Txid, Vec<u8>, u32)> {
+        self.cache
+            .values()
+            .map(|(txid, pending)| (*txid, pending.blob.clone(), pending.confirmations))
+            .collect()
+    }
+}
+
+// decode_reveal extracts the embedded blob from a reveal transaction's
+// first input, if its witness carries an envelope tagged with
+// protocol_id.
+fn decode_reveal(tx: &Transaction, protocol_id: &[u8]) -> Option<Vec<u8>> {
+    let witness = tapscript_witness(&tx.input.first()?.witness)?;
+    let envelopes = extract_push_data(0, witness)?;
+    envelopes
+        .into_iter()
+        .find(|push_data| push_data.starts_with(protocol_id))
+        .map(|push_data| push_data[protocol_id.len()..].to_vec())
+}
@@ -0,0 +1,156 @@
+// Block-range scanner: indexes a contiguous range of blocks, extracting
+// every protocol-tagged blob from every transaction input, while keeping
+// enough history to detect and repair reorgs.
+use std::collections::BTreeMap;
+
+use bitcoin::{BlockHash, Txid};
+
+use crate::{extract_push_data, tapscript_witness, BitcoinError, Relayer, SegmentHeader};
+
+// Scanner walks blocks forward from a starting height, caching the block
+// hash at each indexed height so a later poll can tell whether the chain
+// has reorged out from under it.
+pub struct Scanner<'a> {
+    relayer: &'a Relayer,
+    // height -> block hash, used to detect reorgs.
+    hash_cache: BTreeMap<u64, BlockHash>,
+    // every blob found so far, keyed by where it was found.
+    blobs: BTreeMap<(u64, Txid, usize), Vec<u8>>,
+}
+
+impl<'a> Scanner<'a> {
+    pub fn new(relayer: &'a Relayer) -> Self {
+        Scanner {
+            relayer,
+            hash_cache: BTreeMap::new(),
+            blobs: BTreeMap::new(),
+        }
+    }
+
+    // scan_range indexes every block from start_height through the
+    // current chain tip (inclusive), walking every input of every
+    // transaction. RPC failures are propagated instead of panicking, so
+    // an indexing loop can retry on the next poll.
+    pub fn scan_range(&mut self, start_height: u64) -> Result<(), BitcoinError> {
+        let tip = self
+            .relayer
+            .get_block_count()
+            .map_err(|_| BitcoinError::TransactionErr)?;
+
+        let mut height = start_height;
+        while height <= tip {
+            self.handle_reorg(height)?;
+            self.scan_block(height)?;
+            height += 1;
+        }
+        Ok(())
+    }
+
+    // scan_block indexes a single height, recording its hash and every
+    // protocol-tagged blob found in it.
+    fn scan_block(&mut self, height: u64) -> Result<(), BitcoinError> {
+        let hash = self
+            .relayer
+            .get_block_hash(height)
+            .map_err(|_| BitcoinError::InvalidTxHash)?;
+        let block = self
+            .relayer
+            .get_block(&hash)
+            .map_err(|_| BitcoinError::InvalidTxHash)?;
+
+        let protocol_id = self.relayer.protocol_id();
+        for tx in block.txdata.iter() {
+            let txid = tx.txid();
+            for (input_index, input) in tx.input.iter().enumerate() {
+                if let Some(witness) = tapscript_witness(&input.witness) {
+                    if let Some(envelopes) = extract_push_data(0, witness) {
+                        for push_data in envelopes {
+                            if push_data.starts_with(protocol_id) {
+                                let payload = &push_data[protocol_id.len()..];
+                                // write() always prepends a SegmentHeader
+                                // (even for an unsegmented blob); strip it
+                                // here too so finalized_up_to's blobs are
+                                // directly usable instead of leading with
+                                // 28 bytes of header.
+                                if let Some((_, segment)) = SegmentHeader::decode(payload) {
+                                    self.blobs.insert(
+                                        (height, txid, input_index),
+                                        segment.to_vec(),
+                                    );
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        self.hash_cache.insert(height, hash);
+        Ok(())
+    }
+
+    // handle_reorg compares our cached hash for every height below the
+    // one we're about to scan against the node's current view; on the
+    // first mismatch it walks backward, invalidating cached heights (and
+    // any blobs indexed at them) until the hashes agree again, then
+    // re-indexes the invalidated heights from the now-canonical chain.
+    fn handle_reorg(&mut self, height: u64) -> Result<(), BitcoinError> {
+        if height == 0 {
+            return Ok(());
+        }
+
+        let mut check = height - 1;
+        let mut lowest_invalidated = None;
+        loop {
+            let cached = match self.hash_cache.get(&check) {
+                Some(hash) => *hash,
+                None => break,
+            };
+            let current = self
+                .relayer
+                .get_block_hash(check)
+                .map_err(|_| BitcoinError::InvalidTxHash)?;
+
+            if cached == current {
+                break;
+            }
+
+            self.invalidate_from(check);
+            lowest_invalidated = Some(check);
+
+            if check == 0 {
+                break;
+            }
+            check -= 1;
+        }
+
+        if let Some(from) = lowest_invalidated {
+            for h in from..height {
+                self.scan_block(h)?;
+            }
+        }
+        Ok(())
+    }
+
+    // invalidate_from drops every cached hash and blob at or above height,
+    // forcing them to be re-scanned from the now-canonical chain.
+    fn invalidate_from(&mut self, height: u64) {
+        self.hash_cache.retain(|h, _| *h < height);
+        self.blobs.retain(|(h, _, _), _| *h < height);
+    }
+
+    // finalized_up_to returns only the blobs buried under at least
+    // `confirmations` blocks, based on the highest height indexed so far.
+    pub fn finalized_up_to(&self, confirmations: u64) -> Vec<((u64, Txid, usize), Vec<u8>)> {
+        let tip = match self.hash_cache.keys().next_back() {
+            Some(height) => *height,
+            None => return Vec::new(),
+        };
+
+        self.blobs
+            .iter()
+            .filter(|((height, _, _), _)| tip - height + 1 >= confirmations)
+            .map(|(key, blob)| (*key, blob.clone()))
+            .collect()
+    }
+}